@@ -1,13 +1,77 @@
-use crate::edit::Edit;
+use crate::edit::{apply_order, str_char_to_byte, Edit, OverlappingEdits};
 
 use ropey::{Rope, RopeSlice};
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
+use unicode_segmentation::{GraphemeCursor, GraphemeIncomplete};
+use xxhash_rust::xxh64::Xxh64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+
+    // Counts (lf, crlf, cr) occurrences, a lone '\n' being LF and a lone
+    // '\r' being an old Mac-style CR
+    fn count(text: &str) -> (usize, usize, usize) {
+        let crlf = text.matches("\r\n").count();
+        let lf = text.matches('\n').count() - crlf;
+        let cr = text.matches('\r').count() - crlf;
+
+        (lf, crlf, cr)
+    }
+
+    // A lone CR has no LineEnding of its own, so it never wins
+    fn dominant(text: &str) -> Self {
+        let (lf, crlf, _) = Self::count(text);
+
+        if crlf > lf {
+            LineEnding::Crlf
+        } else {
+            LineEnding::Lf
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineEndingReport {
+    pub line_ending: LineEnding,
+    pub mixed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Word,
+    Whitespace,
+    LineBreak,
+    Punctuation,
+}
+
+// The chars ropey treats as line breaks
+fn is_line_break(c: char) -> bool {
+    matches!(
+        c,
+        '\n' | '\r' | '\u{000B}' | '\u{000C}' | '\u{0085}' | '\u{2028}' | '\u{2029}'
+    )
+}
 
 #[derive(Debug)]
 pub struct Buffer {
     content: Rope,
     path: Option<PathBuf>,
     modified: bool,
+    word_chars: HashSet<char>,
+    line_ending: LineEnding,
 }
 
 impl Buffer {
@@ -16,6 +80,8 @@ impl Buffer {
             content: Rope::new(),
             path: None,
             modified: false,
+            word_chars: HashSet::new(),
+            line_ending: LineEnding::default(),
         }
     }
 
@@ -24,9 +90,33 @@ impl Buffer {
             content: Rope::from_str(text),
             path: None,
             modified: false,
+            word_chars: HashSet::new(),
+            line_ending: LineEnding::dominant(text),
         }
     }
 
+    // Rewrites every line ending, lone CRs included, to the dominant style,
+    // reporting whether mixed endings were found so a UI can warn about the
+    // normalization
+    pub fn from_str_normalized(text: &str) -> (Self, LineEndingReport) {
+        let line_ending = LineEnding::dominant(text);
+        let (lf, crlf, cr) = LineEnding::count(text);
+
+        let mixed = [lf, crlf, cr].iter().filter(|&&count| count > 0).count() > 1;
+        let normalized = match line_ending {
+            _ if !mixed => text.to_string(),
+            LineEnding::Lf => text.replace("\r\n", "\n").replace('\r', "\n"),
+            LineEnding::Crlf => text
+                .replace("\r\n", "\n")
+                .replace('\r', "\n")
+                .replace('\n', "\r\n"),
+        };
+
+        let report = LineEndingReport { line_ending, mixed };
+
+        (Self::from_str(&normalized), report)
+    }
+
     pub fn content(&self) -> &Rope {
         &self.content
     }
@@ -39,6 +129,82 @@ impl Buffer {
         self.content.len_lines()
     }
 
+    // Streams the chunks through xxh64, so the hash depends only on the text
+    // and not on chunking. Unlike DefaultHasher its output is fixed across
+    // Rust releases, which makes it safe to persist
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = Xxh64::new(0);
+
+        for chunk in self.content.chunks() {
+            hasher.update(chunk.as_bytes());
+        }
+
+        hasher.digest()
+    }
+
+    pub fn len_graphemes(&self) -> usize {
+        self.grapheme_boundaries().count()
+    }
+
+    // Removes everything past the first `count` graphemes, returning the
+    // removed text
+    pub fn truncate_to_graphemes(&mut self, count: usize) -> String {
+        let cut = match count {
+            0 => Some(0),
+            _ => self.grapheme_boundaries().nth(count - 1),
+        };
+
+        match cut.map(|byte_idx| self.content.byte_to_char(byte_idx)) {
+            Some(char_idx) if char_idx < self.len_chars() => {
+                self.apply_edit(&Edit::delete(char_idx, self.len_chars()))
+            }
+            _ => String::new(),
+        }
+    }
+
+    // Byte indices of the end of each grapheme
+    fn grapheme_boundaries(&self) -> impl Iterator<Item = usize> + '_ {
+        std::iter::successors(self.next_grapheme_boundary(0), |&byte_idx| {
+            self.next_grapheme_boundary(byte_idx)
+        })
+    }
+
+    // A fresh cursor per boundary, fed rope chunks on demand. Reusing one
+    // cursor across chunks misplaces boundaries inside regional indicator
+    // pairs that straddle a chunk edge
+    fn next_grapheme_boundary(&self, byte_idx: usize) -> Option<usize> {
+        let len_bytes = self.content.len_bytes();
+
+        if byte_idx >= len_bytes {
+            return None;
+        }
+
+        let mut cursor = GraphemeCursor::new(byte_idx, len_bytes, true);
+        let (mut chunk, mut chunk_start, _, _) = self.content.chunk_at_byte(byte_idx);
+
+        loop {
+            match cursor.next_boundary(chunk, chunk_start) {
+                Ok(boundary) => return boundary,
+                Err(GraphemeIncomplete::NextChunk) => {
+                    chunk_start += chunk.len();
+                    (chunk, _, _, _) = self.content.chunk_at_byte(chunk_start);
+                }
+                Err(GraphemeIncomplete::PreContext(context_end)) => {
+                    let (context, context_start, _, _) =
+                        self.content.chunk_at_byte(context_end - 1);
+                    cursor.provide_context(context, context_start);
+                }
+                Err(_) => unreachable!("Invalid grapheme cursor state"),
+            }
+        }
+    }
+
+    // Like len_lines, minus the empty line ropey reports after a final line
+    // break
+    pub fn len_lines_content(&self) -> usize {
+        self.len_lines() - usize::from(self.has_trailing_newline())
+    }
+
     pub fn path(&self) -> Option<&PathBuf> {
         self.path.as_ref()
     }
@@ -55,19 +221,82 @@ impl Buffer {
         self.modified = modified;
     }
 
-    pub fn apply(&mut self, edits: &[Edit]) -> Vec<String> {
-        let mut indexed_edits: Vec<_> = edits.iter().enumerate().collect();
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        self.line_ending = line_ending;
+    }
+
+    pub fn word_chars(&self) -> &HashSet<char> {
+        &self.word_chars
+    }
+
+    // Extra characters treated as part of a word, on top of alphanumerics
+    pub fn set_word_chars(&mut self, word_chars: HashSet<char>) {
+        self.word_chars = word_chars;
+    }
+
+    pub fn is_word_char(&self, c: char) -> bool {
+        c.is_alphanumeric() || self.word_chars.contains(&c)
+    }
+
+    fn char_class(&self, c: char) -> CharClass {
+        match c {
+            _ if self.is_word_char(c) => CharClass::Word,
+            _ if is_line_break(c) => CharClass::LineBreak,
+            _ if c.is_whitespace() => CharClass::Whitespace,
+            _ => CharClass::Punctuation,
+        }
+    }
+
+    // The run of same-class chars around a position, as selected by a
+    // double-click: a word, a stretch of whitespace, or a punctuation run.
+    // Line breaks are selected one at a time, "\r\n" as a single unit, so
+    // runs never span lines
+    pub fn word_range_at(&self, char_idx: usize) -> Option<(usize, usize)> {
+        let class = self.char_class(self.char_at(char_idx)?);
+        let same_class = |c: &char| self.char_class(*c) == class;
+
+        if class == CharClass::LineBreak {
+            let starts_crlf =
+                |idx: usize| self.char_at(idx) == Some('\r') && self.char_at(idx + 1) == Some('\n');
 
-        // Sort in reverse order to avoid offset issues
-        indexed_edits.sort_by(|(_, a), (_, b)| b.start.cmp(&a.start));
+            return Some(match char_idx {
+                _ if starts_crlf(char_idx) => (char_idx, char_idx + 2),
+                _ if char_idx > 0 && starts_crlf(char_idx - 1) => (char_idx - 1, char_idx + 1),
+                _ => (char_idx, char_idx + 1),
+            });
+        }
+
+        let before = self
+            .content
+            .chars_at(char_idx)
+            .reversed()
+            .take_while(same_class)
+            .count();
+        let after = self
+            .content
+            .chars_at(char_idx)
+            .take_while(same_class)
+            .count();
 
+        Some((char_idx - before, char_idx + after))
+    }
+
+    // Overlapping edits are rejected as a whole and leave the buffer
+    // untouched. See apply_order for how edits sharing a start are ordered
+    pub fn apply(&mut self, edits: &[Edit]) -> Result<Vec<String>, OverlappingEdits> {
+        let order = apply_order(edits)?;
         let mut deleted_texts = vec![String::new(); edits.len()];
 
-        for (original_index, edit) in indexed_edits {
-            deleted_texts[original_index] = self.apply_edit(edit);
+        // Apply back to front to avoid offset issues
+        for index in order.into_iter().rev() {
+            deleted_texts[index] = self.apply_edit(&edits[index]);
         }
 
-        deleted_texts
+        Ok(deleted_texts)
     }
 
     pub fn apply_edit(&mut self, edit: &Edit) -> String {
@@ -84,6 +313,10 @@ impl Buffer {
         deleted_text
     }
 
+    pub fn replace_range(&mut self, start: usize, end: usize, text: &str) -> String {
+        self.apply_edit(&Edit::replace(start, end, text))
+    }
+
     fn handle_insertion(&mut self, edit: &Edit) -> String {
         self.content.insert(edit.start, &edit.text);
 
@@ -119,6 +352,115 @@ impl Buffer {
         self.content.line(line_idx)
     }
 
+    // Yields each line with its starting char index, including the trailing
+    // empty line ropey reports after a final newline
+    pub fn lines_with_ranges(&self) -> impl Iterator<Item = (usize, RopeSlice<'_>)> + '_ {
+        self.content.lines().scan(0, |line_start, line| {
+            let start = *line_start;
+            *line_start += line.len_chars();
+
+            Some((start, line))
+        })
+    }
+
+    pub fn words(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let mut chars = self.content.chars().enumerate().peekable();
+
+        std::iter::from_fn(move || {
+            let (start, _) = chars.find(|&(_, c)| self.is_word_char(c))?;
+            let mut end = start + 1;
+
+            while chars.next_if(|&(_, c)| self.is_word_char(c)).is_some() {
+                end += 1;
+            }
+
+            Some((start, end))
+        })
+    }
+
+    // Distinct identifier-like words with the position of their first use
+    pub fn symbols(&self) -> Vec<(String, usize)> {
+        let mut seen = HashSet::new();
+
+        self.identifiers()
+            .filter(|(symbol, _)| seen.insert(symbol.clone()))
+            .collect()
+    }
+
+    // Words extending the prefix, those used closest to `near` first
+    pub fn complete_prefix(&self, prefix: &str, near: usize) -> Vec<String> {
+        let mut distances: HashMap<String, usize> = HashMap::new();
+
+        for (word, start) in self.identifiers() {
+            if word.starts_with(prefix) && word != prefix {
+                let distance = start.abs_diff(near);
+                let closest = distances.entry(word).or_insert(distance);
+                *closest = (*closest).min(distance);
+            }
+        }
+
+        let mut completions: Vec<_> = distances.into_iter().collect();
+        completions.sort_by(|(a, a_distance), (b, b_distance)| {
+            a_distance.cmp(b_distance).then_with(|| a.cmp(b))
+        });
+
+        completions.into_iter().map(|(word, _)| word).collect()
+    }
+
+    fn identifiers(&self) -> impl Iterator<Item = (String, usize)> + '_ {
+        self.words()
+            .map(|(start, end)| (self.content.slice(start..end).to_string(), start))
+            .filter(|(word, _)| !word.starts_with(char::is_numeric))
+    }
+
+    // Paragraphs are runs of non-blank lines, each range ending after the
+    // last line's newline
+    pub fn paragraphs(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let mut lines = self.lines_with_ranges().peekable();
+        let is_blank = |line: &RopeSlice| line.chars().all(char::is_whitespace);
+
+        std::iter::from_fn(move || {
+            let (start, first_line) = lines.find(|(_, line)| !is_blank(line))?;
+            let mut end = start + first_line.len_chars();
+
+            while let Some((line_start, line)) = lines.next_if(|(_, line)| !is_blank(line)) {
+                end = line_start + line.len_chars();
+            }
+
+            Some((start, end))
+        })
+    }
+
+    // Char index where the line's content ends, before its "\r\n" or any
+    // single-char break ropey recognizes
+    pub fn line_content_end(&self, line_idx: usize) -> usize {
+        let line = self.line(line_idx);
+        let mut chars = line.chars_at(line.len_chars()).reversed();
+
+        let terminator_len = match (chars.next(), chars.next()) {
+            (Some('\n'), Some('\r')) => 2,
+            (Some(c), _) if is_line_break(c) => 1,
+            _ => 0,
+        };
+
+        self.line_to_char(line_idx) + line.len_chars() - terminator_len
+    }
+
+    // None for offsets past the end or inside a multibyte char
+    pub fn byte_to_char(&self, byte_idx: usize) -> Option<usize> {
+        if byte_idx > self.content.len_bytes() {
+            return None;
+        }
+
+        let char_idx = self.content.byte_to_char(byte_idx);
+
+        (self.content.char_to_byte(char_idx) == byte_idx).then_some(char_idx)
+    }
+
+    pub fn char_to_byte(&self, char_idx: usize) -> Option<usize> {
+        (char_idx <= self.len_chars()).then(|| self.content.char_to_byte(char_idx))
+    }
+
     pub fn char_to_line(&self, char_idx: usize) -> usize {
         self.content.char_to_line(char_idx)
     }
@@ -134,6 +476,95 @@ impl Buffer {
             None
         }
     }
+
+    // Searches chunk by chunk so the rope is never stringified. Going forward
+    // it finds the first match starting at or after from_char, going backward
+    // the last match starting before it
+    pub fn search(&self, pattern: &str, from_char: usize, forward: bool) -> Option<(usize, usize)> {
+        if pattern.is_empty() || from_char > self.len_chars() {
+            return None;
+        }
+
+        let start = if forward {
+            self.search_forward(pattern, from_char)
+        } else {
+            self.search_backward(pattern, from_char)
+        }?;
+
+        Some((start, start + pattern.chars().count()))
+    }
+
+    // Non-overlapping matches in document order
+    pub fn find_all(&self, pattern: &str) -> Vec<(usize, usize)> {
+        std::iter::successors(self.search(pattern, 0, true), |&(_, end)| {
+            self.search(pattern, end, true)
+        })
+        .collect()
+    }
+
+    fn search_forward(&self, pattern: &str, from_char: usize) -> Option<usize> {
+        // Enough of the previous chunks to catch matches straddling a boundary
+        let overlap = pattern.chars().count() - 1;
+
+        let mut window = String::new();
+        let mut window_start = from_char;
+
+        for chunk in self.content.slice(from_char..).chunks() {
+            window.push_str(chunk);
+
+            if let Some(byte_idx) = window.find(pattern) {
+                return Some(window_start + window[..byte_idx].chars().count());
+            }
+
+            let dropped = window.chars().count().saturating_sub(overlap);
+            window.drain(..str_char_to_byte(&window, dropped));
+            window_start += dropped;
+        }
+
+        None
+    }
+
+    fn search_backward(&self, pattern: &str, from_char: usize) -> Option<usize> {
+        let overlap = pattern.chars().count() - 1;
+        let end = (from_char + overlap).min(self.len_chars());
+        let slice = self.content.slice(..end);
+
+        let mut window = String::new();
+        let mut window_end = end;
+
+        for chunk in slice.chunks_at_char(end).0.reversed() {
+            window.insert_str(0, chunk);
+
+            let window_start = window_end - window.chars().count();
+
+            if let Some(byte_idx) = window.rfind(pattern) {
+                return Some(window_start + window[..byte_idx].chars().count());
+            }
+
+            window.truncate(str_char_to_byte(&window, overlap));
+            window_end = window_start + window.chars().count();
+        }
+
+        None
+    }
+
+    pub fn try_slice(&self, start: usize, end: usize) -> Option<RopeSlice<'_>> {
+        if start <= end && end <= self.len_chars() {
+            Some(self.content.slice(start..end))
+        } else {
+            None
+        }
+    }
+
+    // Ropey reports an extra empty line after a final line break, so callers
+    // should check this rather than inspecting the last line. Any break ropey
+    // recognizes counts, including a lone '\r'
+    pub fn has_trailing_newline(&self) -> bool {
+        match self.len_chars() {
+            0 => false,
+            len => is_line_break(self.content.char(len - 1)),
+        }
+    }
 }
 
 impl Default for Buffer {
@@ -141,3 +572,519 @@ impl Default for Buffer {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_finds_match_near_end_of_large_buffer() {
+        let mut text = "abcdefghij\n".repeat(300_000);
+        text.push_str("needle");
+        text.push_str("tail");
+        let buffer = Buffer::from_str(&text);
+        let needle_start = buffer.len_chars() - 10;
+
+        assert!(buffer.content().chunks().count() > 1);
+        assert_eq!(
+            buffer.search("needle", 0, true),
+            Some((needle_start, needle_start + 6))
+        );
+        assert_eq!(
+            buffer.search("needle", buffer.len_chars(), false),
+            Some((needle_start, needle_start + 6))
+        );
+    }
+
+    #[test]
+    fn search_matches_straddling_chunk_boundaries() {
+        let text = "abcdéfghij".repeat(5_000);
+        let buffer = Buffer::from_str(&text);
+        let chars: Vec<char> = text.chars().collect();
+        let expected: Vec<usize> = (0..chars.len() - 2)
+            .filter(|&i| chars[i..i + 3] == ['j', 'a', 'b'])
+            .collect();
+
+        let mut forward = Vec::new();
+        let mut from = 0;
+        while let Some((start, _)) = buffer.search("jab", from, true) {
+            forward.push(start);
+            from = start + 1;
+        }
+
+        let mut backward = Vec::new();
+        let mut from = buffer.len_chars();
+        while let Some((start, _)) = buffer.search("jab", from, false) {
+            backward.push(start);
+            from = start;
+        }
+        backward.reverse();
+
+        assert_eq!(forward, expected);
+        assert_eq!(backward, expected);
+    }
+
+    #[test]
+    fn search_forward_starts_at_from_char() {
+        let buffer = Buffer::from_str("foo boo zoo");
+
+        assert_eq!(buffer.search("oo", 0, true), Some((1, 3)));
+        assert_eq!(buffer.search("oo", 1, true), Some((1, 3)));
+        assert_eq!(buffer.search("oo", 2, true), Some((5, 7)));
+        assert_eq!(buffer.search("oo", 10, true), None);
+    }
+
+    #[test]
+    fn search_backward_finds_match_starting_before_from_char() {
+        let buffer = Buffer::from_str("foo boo zoo");
+
+        assert_eq!(buffer.search("oo", 11, false), Some((9, 11)));
+        assert_eq!(buffer.search("oo", 9, false), Some((5, 7)));
+        assert_eq!(buffer.search("oo", 6, false), Some((5, 7)));
+        assert_eq!(buffer.search("oo", 1, false), None);
+    }
+
+    #[test]
+    fn search_rejects_empty_pattern_and_out_of_range_start() {
+        let buffer = Buffer::from_str("abc");
+
+        assert_eq!(buffer.search("", 0, true), None);
+        assert_eq!(buffer.search("a", 4, true), None);
+        assert_eq!(buffer.search("a", 4, false), None);
+    }
+
+    #[test]
+    fn apply_rejects_overlapping_edits_without_touching_buffer() {
+        let mut buffer = Buffer::from_str("hello world");
+        let edits = [Edit::replace(0, 5, "x"), Edit::replace(3, 8, "y")];
+
+        assert_eq!(
+            buffer.apply(&edits),
+            Err(OverlappingEdits {
+                first: 0,
+                second: 1
+            })
+        );
+        assert_eq!(buffer.content().to_string(), "hello world");
+        assert!(!buffer.is_modified());
+    }
+
+    #[test]
+    fn apply_accepts_adjacent_edits() {
+        let mut buffer = Buffer::from_str("hello world");
+        let edits = [Edit::replace(0, 5, "bye"), Edit::replace(5, 6, "_")];
+
+        assert_eq!(
+            buffer.apply(&edits),
+            Ok(vec!["hello".to_string(), " ".to_string()])
+        );
+        assert_eq!(buffer.content().to_string(), "bye_world");
+    }
+
+    #[test]
+    fn apply_orders_insert_before_delete_at_same_start() {
+        let insert_first = [Edit::insert(3, "X"), Edit::delete(3, 6)];
+        let delete_first = [Edit::delete(3, 6), Edit::insert(3, "X")];
+
+        for edits in [&insert_first, &delete_first] {
+            let mut buffer = Buffer::from_str("abcdefgh");
+            buffer.apply(edits).unwrap();
+
+            assert_eq!(buffer.content().to_string(), "abcXgh");
+        }
+    }
+
+    #[test]
+    fn apply_keeps_input_order_of_inserts_at_same_position() {
+        let mut buffer = Buffer::from_str("0123");
+
+        buffer
+            .apply(&[Edit::insert(2, "a"), Edit::insert(2, "b")])
+            .unwrap();
+
+        assert_eq!(buffer.content().to_string(), "01ab23");
+    }
+
+    #[test]
+    fn content_hash_is_pinned_to_xxh64() {
+        // Persisted undo files are keyed by this value, so it must not drift
+        assert_eq!(Buffer::from_str("").content_hash(), 0xef46db3751d8e999);
+    }
+
+    #[test]
+    fn from_str_normalized_converts_mixed_endings_to_dominant_style() {
+        let (buffer, report) = Buffer::from_str_normalized("a\r\nb\nc\r\n");
+
+        assert_eq!(
+            report,
+            LineEndingReport {
+                line_ending: LineEnding::Crlf,
+                mixed: true
+            }
+        );
+        assert_eq!(buffer.content().to_string(), "a\r\nb\r\nc\r\n");
+        assert_eq!(buffer.line_ending(), LineEnding::Crlf);
+        assert!(!buffer.is_modified());
+    }
+
+    #[test]
+    fn from_str_normalized_prefers_lf_when_it_dominates() {
+        let (buffer, report) = Buffer::from_str_normalized("a\r\nb\nc\n");
+
+        assert_eq!(report.line_ending, LineEnding::Lf);
+        assert!(report.mixed);
+        assert_eq!(buffer.content().to_string(), "a\nb\nc\n");
+    }
+
+    #[test]
+    fn from_str_normalized_leaves_consistent_endings_alone() {
+        let (buffer, report) = Buffer::from_str_normalized("a\r\nb\r\n");
+
+        assert!(!report.mixed);
+        assert_eq!(buffer.content().to_string(), "a\r\nb\r\n");
+    }
+
+    #[test]
+    fn word_range_at_selects_word_whitespace_or_punctuation_run() {
+        let buffer = Buffer::from_str("foo  bar..baz");
+
+        assert_eq!(buffer.word_range_at(1), Some((0, 3)));
+        assert_eq!(buffer.word_range_at(3), Some((3, 5)));
+        assert_eq!(buffer.word_range_at(8), Some((8, 10)));
+        assert_eq!(buffer.word_range_at(13), None);
+    }
+
+    #[test]
+    fn word_range_at_stops_at_line_breaks() {
+        let buffer = Buffer::from_str("foo  \n  bar.baz");
+
+        assert_eq!(buffer.word_range_at(4), Some((3, 5)));
+        assert_eq!(buffer.word_range_at(5), Some((5, 6)));
+        assert_eq!(buffer.word_range_at(6), Some((6, 8)));
+    }
+
+    #[test]
+    fn word_range_at_honors_word_chars() {
+        let mut buffer = Buffer::from_str("foo_bar");
+        assert_eq!(buffer.word_range_at(1), Some((0, 3)));
+
+        buffer.set_word_chars(HashSet::from(['_']));
+        assert_eq!(buffer.word_range_at(1), Some((0, 7)));
+    }
+
+    #[test]
+    fn len_lines_content_ignores_phantom_trailing_line() {
+        assert_eq!(Buffer::from_str("a\n").len_lines_content(), 1);
+        assert_eq!(Buffer::from_str("a\nb").len_lines_content(), 2);
+        assert_eq!(Buffer::from_str("a\r\nb\r\n").len_lines_content(), 2);
+        assert_eq!(Buffer::from_str("").len_lines_content(), 1);
+    }
+
+    #[test]
+    fn len_lines_content_matches_ropey_line_breaks() {
+        for text in ["a\r", "a\u{2028}", "a\u{0085}", "a\n\r"] {
+            let buffer = Buffer::from_str(text);
+            let last_line = buffer.line(buffer.len_lines() - 1);
+
+            assert_eq!(last_line.len_chars(), 0, "{text:?}");
+            assert_eq!(
+                buffer.len_lines_content(),
+                buffer.len_lines() - 1,
+                "{text:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn word_chars_default_to_alphanumerics() {
+        let buffer = Buffer::from_str("foo_bar");
+
+        assert_eq!(buffer.words().collect::<Vec<_>>(), vec![(0, 3), (4, 7)]);
+        assert_eq!(buffer.word_range_at(3), Some((3, 4)));
+    }
+
+    #[test]
+    fn word_chars_extend_words() {
+        let mut buffer = Buffer::from_str("foo_bar $x lisp-case");
+        buffer.set_word_chars(HashSet::from(['_', '$', '-']));
+
+        assert!(buffer.is_word_char('_'));
+        assert_eq!(
+            buffer.words().collect::<Vec<_>>(),
+            vec![(0, 7), (8, 10), (11, 20)]
+        );
+    }
+
+    #[test]
+    fn has_trailing_newline_without_final_newline() {
+        assert!(!Buffer::from_str("a\nb").has_trailing_newline());
+        assert!(!Buffer::new().has_trailing_newline());
+    }
+
+    #[test]
+    fn has_trailing_newline_with_several_final_newlines() {
+        let buffer = Buffer::from_str("a\n\n\n");
+
+        assert!(buffer.has_trailing_newline());
+        assert_eq!(buffer.len_lines(), 4);
+        assert_eq!(buffer.len_lines_content(), 3);
+    }
+
+    #[test]
+    fn lines_with_ranges_includes_phantom_trailing_line() {
+        let buffer = Buffer::from_str("ab\ncd\n");
+        let lines: Vec<_> = buffer
+            .lines_with_ranges()
+            .map(|(start, line)| (start, line.to_string()))
+            .collect();
+
+        assert_eq!(
+            lines,
+            vec![
+                (0, "ab\n".to_string()),
+                (3, "cd\n".to_string()),
+                (6, String::new())
+            ]
+        );
+    }
+
+    #[test]
+    fn lines_with_ranges_on_empty_buffer() {
+        let buffer = Buffer::new();
+        let lines: Vec<_> = buffer
+            .lines_with_ranges()
+            .map(|(start, line)| (start, line.len_chars()))
+            .collect();
+
+        assert_eq!(lines, vec![(0, 0)]);
+    }
+
+    #[test]
+    fn try_slice_returns_valid_ranges() {
+        let buffer = Buffer::from_str("hello");
+
+        assert_eq!(buffer.try_slice(1, 4).unwrap().to_string(), "ell");
+        assert_eq!(buffer.try_slice(5, 5).unwrap().len_chars(), 0);
+    }
+
+    #[test]
+    fn try_slice_rejects_reversed_and_out_of_bounds_ranges() {
+        let buffer = Buffer::from_str("hello");
+
+        assert!(buffer.try_slice(4, 1).is_none());
+        assert!(buffer.try_slice(2, 6).is_none());
+        assert!(buffer.try_slice(7, 9).is_none());
+    }
+
+    #[test]
+    fn content_hash_equal_for_identical_content_regardless_of_chunking() {
+        let text = "abcdefghij\n".repeat(10_000);
+        let loaded = Buffer::from_str(&text);
+        let mut typed = Buffer::new();
+
+        for line in text.split_inclusive('\n') {
+            typed.apply_edit(&Edit::insert(typed.len_chars(), line));
+        }
+
+        assert!(loaded.content().chunks().count() > 1);
+        assert_eq!(loaded.content_hash(), typed.content_hash());
+    }
+
+    #[test]
+    fn content_hash_changes_after_one_char_edit() {
+        let mut buffer = Buffer::from_str("hello world");
+        let before = buffer.content_hash();
+
+        buffer.apply_edit(&Edit::replace(0, 1, "j"));
+
+        assert_ne!(buffer.content_hash(), before);
+    }
+
+    #[test]
+    fn words_yields_word_ranges() {
+        let buffer = Buffer::from_str("one two");
+
+        assert_eq!(buffer.words().collect::<Vec<_>>(), vec![(0, 3), (4, 7)]);
+        assert_eq!(Buffer::from_str(" .. ").words().count(), 0);
+    }
+
+    #[test]
+    fn paragraphs_yields_runs_of_non_blank_lines() {
+        let buffer = Buffer::from_str("a\nb\n\n  \nc\nd");
+
+        assert_eq!(
+            buffer.paragraphs().collect::<Vec<_>>(),
+            vec![(0, 4), (8, 11)]
+        );
+        assert_eq!(Buffer::new().paragraphs().count(), 0);
+    }
+
+    #[test]
+    fn len_graphemes_counts_clusters_not_chars() {
+        let buffer = Buffer::from_str("e\u{301}🇦🇷x👍🏽");
+
+        assert_eq!(buffer.len_chars(), 7);
+        assert_eq!(buffer.len_graphemes(), 4);
+        assert_eq!(Buffer::new().len_graphemes(), 0);
+    }
+
+    #[test]
+    fn len_graphemes_across_chunk_boundaries() {
+        let text = "e\u{301}🇦🇷x👍🏽".repeat(20_000);
+        let buffer = Buffer::from_str(&text);
+
+        assert!(buffer.content().chunks().count() > 1);
+        assert_eq!(buffer.len_graphemes(), 80_000);
+    }
+
+    #[test]
+    fn truncate_to_graphemes_keeps_whole_clusters() {
+        let mut buffer = Buffer::from_str("e\u{301}🇦🇷x👍🏽");
+
+        assert_eq!(buffer.truncate_to_graphemes(10), "");
+        assert!(!buffer.is_modified());
+
+        assert_eq!(buffer.truncate_to_graphemes(2), "x👍🏽");
+        assert_eq!(buffer.content().to_string(), "e\u{301}🇦🇷");
+        assert!(buffer.is_modified());
+
+        assert_eq!(buffer.truncate_to_graphemes(0), "e\u{301}🇦🇷");
+        assert_eq!(buffer.len_chars(), 0);
+    }
+
+    #[test]
+    fn find_all_counts_every_occurrence() {
+        let buffer = Buffer::from_str("ab ab aaa ab ab");
+
+        assert_eq!(buffer.find_all("ab").len(), 4);
+        assert!(buffer.find_all("zz").is_empty());
+    }
+
+    #[test]
+    fn find_all_returns_non_overlapping_matches() {
+        assert_eq!(
+            Buffer::from_str("aaaa").find_all("aa"),
+            vec![(0, 2), (2, 4)]
+        );
+    }
+
+    #[test]
+    fn line_content_end_excludes_crlf_terminator() {
+        let buffer = Buffer::from_str("ab\r\ncd\r\nef");
+
+        assert_eq!(buffer.line_content_end(0), 2);
+        assert_eq!(buffer.line_content_end(1), 6);
+        assert_eq!(buffer.line_content_end(2), 10);
+    }
+
+    #[test]
+    fn line_content_end_with_mixed_and_missing_terminators() {
+        let buffer = Buffer::from_str("ab\ncd\ref\n");
+
+        assert_eq!(buffer.line_content_end(0), 2);
+        assert_eq!(buffer.line_content_end(1), 5);
+        assert_eq!(buffer.line_content_end(2), 8);
+        assert_eq!(buffer.line_content_end(3), 9);
+        assert_eq!(Buffer::new().line_content_end(0), 0);
+    }
+
+    #[test]
+    fn byte_char_conversion_round_trips_around_multibyte_chars() {
+        let buffer = Buffer::from_str("aé😀b");
+
+        assert_eq!(buffer.char_to_byte(2), Some(3));
+        assert_eq!(buffer.byte_to_char(3), Some(2));
+        assert_eq!(buffer.char_to_byte(4), Some(8));
+
+        for char_idx in 0..=buffer.len_chars() {
+            let byte_idx = buffer.char_to_byte(char_idx).unwrap();
+            assert_eq!(buffer.byte_to_char(byte_idx), Some(char_idx));
+        }
+    }
+
+    #[test]
+    fn byte_char_conversion_rejects_invalid_offsets() {
+        let buffer = Buffer::from_str("aé😀b");
+
+        assert_eq!(buffer.byte_to_char(2), None);
+        assert_eq!(buffer.byte_to_char(5), None);
+        assert_eq!(buffer.byte_to_char(9), None);
+        assert_eq!(buffer.char_to_byte(5), None);
+    }
+
+    #[test]
+    fn replace_range_returns_replaced_text() {
+        let mut buffer = Buffer::from_str("hello world");
+
+        assert_eq!(buffer.replace_range(2, 8, "XY"), "llo wo");
+        assert_eq!(buffer.content().to_string(), "heXYrld");
+        assert!(buffer.is_modified());
+    }
+
+    #[test]
+    fn replace_range_with_empty_range_or_text() {
+        let mut buffer = Buffer::from_str("abc");
+
+        assert_eq!(buffer.replace_range(1, 1, "Z"), "");
+        assert_eq!(buffer.replace_range(0, 2, ""), "aZ");
+        assert_eq!(buffer.content().to_string(), "bc");
+    }
+
+    #[test]
+    fn symbols_lists_distinct_identifiers_with_first_position() {
+        let buffer = Buffer::from_str("foo 12 foobar fob foo fo");
+
+        assert_eq!(
+            buffer.symbols(),
+            vec![
+                ("foo".to_string(), 0),
+                ("foobar".to_string(), 7),
+                ("fob".to_string(), 14),
+                ("fo".to_string(), 22)
+            ]
+        );
+    }
+
+    #[test]
+    fn complete_prefix_orders_by_proximity() {
+        let buffer = Buffer::from_str("foo 12 foobar fob foo fo");
+
+        assert_eq!(
+            buffer.complete_prefix("fo", 22),
+            vec!["foo", "fob", "foobar"]
+        );
+        assert_eq!(
+            buffer.complete_prefix("fo", 0),
+            vec!["foo", "foobar", "fob"]
+        );
+        assert!(buffer.complete_prefix("bar", 0).is_empty());
+    }
+    #[test]
+    fn word_range_at_selects_a_single_line_break() {
+        let lf = Buffer::from_str("a\n\n\nb");
+        let crlf = Buffer::from_str("a\r\n\r\nb");
+
+        assert_eq!(lf.word_range_at(1), Some((1, 2)));
+        assert_eq!(lf.word_range_at(2), Some((2, 3)));
+        assert_eq!(crlf.word_range_at(1), Some((1, 3)));
+        assert_eq!(crlf.word_range_at(2), Some((1, 3)));
+        assert_eq!(crlf.word_range_at(3), Some((3, 5)));
+    }
+    #[test]
+    fn from_str_normalized_rewrites_lone_carriage_returns() {
+        let (lf_buffer, lf_report) = Buffer::from_str_normalized("a\rb\r\nc\n");
+        let (crlf_buffer, crlf_report) = Buffer::from_str_normalized("a\rb\r\nc\r\n");
+        let (cr_buffer, cr_report) = Buffer::from_str_normalized("a\rb\r");
+
+        assert_eq!(lf_buffer.content().to_string(), "a\nb\nc\n");
+        assert_eq!(lf_report.line_ending, LineEnding::Lf);
+        assert!(lf_report.mixed);
+
+        assert_eq!(crlf_buffer.content().to_string(), "a\r\nb\r\nc\r\n");
+        assert_eq!(crlf_report.line_ending, LineEnding::Crlf);
+        assert!(crlf_report.mixed);
+
+        assert_eq!(cr_buffer.content().to_string(), "a\rb\r");
+        assert!(!cr_report.mixed);
+    }
+}
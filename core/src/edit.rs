@@ -1,4 +1,12 @@
+use std::fmt;
+
+// Positions are char indices, not byte offsets; use Buffer::byte_to_char to
+// convert offsets coming from byte-based tooling
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "persistent-undo",
+    derive(serde::Serialize, serde::Deserialize)
+)]
 pub struct Edit {
     pub start: usize,
     pub end: usize,
@@ -46,3 +54,147 @@ impl Edit {
         self.start == self.end && self.text.is_empty()
     }
 }
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverlappingEdits {
+    pub first: usize,
+    pub second: usize,
+}
+
+impl fmt::Display for OverlappingEdits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "edits {} and {} overlap", self.first, self.second)
+    }
+}
+
+impl std::error::Error for OverlappingEdits {}
+
+// Indices of the edits in document order. At equal starts inserts come before
+// wider edits, and edits that still tie keep their input order, so inserts at
+// one position end up in the order given. Overlapping ranges are rejected as
+// applying them would touch text already shifted by another edit
+pub fn apply_order(edits: &[Edit]) -> Result<Vec<usize>, OverlappingEdits> {
+    let mut order: Vec<usize> = (0..edits.len()).collect();
+    order.sort_by_key(|&index| (edits[index].start, edits[index].end));
+
+    for pair in order.windows(2) {
+        if edits[pair[0]].end > edits[pair[1]].start {
+            return Err(OverlappingEdits {
+                first: pair[0].min(pair[1]),
+                second: pair[0].max(pair[1]),
+            });
+        }
+    }
+
+    Ok(order)
+}
+
+// Applies char-indexed edits to a plain string the same way Buffer::apply
+// does, without needing a rope
+pub fn apply_edits_to_str(input: &str, edits: &[Edit]) -> Result<String, OverlappingEdits> {
+    let order = apply_order(edits)?;
+    let mut output = input.to_string();
+
+    // Apply back to front to avoid offset issues
+    for index in order.into_iter().rev() {
+        let edit = &edits[index];
+        let start = str_char_to_byte(&output, edit.start);
+        let end = str_char_to_byte(&output, edit.end);
+
+        output.replace_range(start..end, &edit.text);
+    }
+
+    Ok(output)
+}
+
+pub(crate) fn str_char_to_byte(text: &str, char_idx: usize) -> usize {
+    text.char_indices()
+        .nth(char_idx)
+        .map_or(text.len(), |(byte_idx, _)| byte_idx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_order_rejects_overlaps() {
+        let edits = [Edit::delete(2, 5), Edit::insert(3, "x")];
+
+        assert_eq!(
+            apply_order(&edits),
+            Err(OverlappingEdits {
+                first: 0,
+                second: 1
+            })
+        );
+    }
+
+    #[test]
+    fn apply_order_puts_inserts_first_at_equal_starts() {
+        let edits = [
+            Edit::delete(3, 6),
+            Edit::insert(3, "X"),
+            Edit::insert(0, "Y"),
+        ];
+
+        assert_eq!(apply_order(&edits), Ok(vec![2, 1, 0]));
+    }
+
+    #[test]
+    fn apply_edits_to_str_rejects_overlaps() {
+        let edits = [Edit::replace(0, 5, "x"), Edit::replace(3, 8, "y")];
+
+        assert!(apply_edits_to_str("hello world", &edits).is_err());
+    }
+
+    #[test]
+    fn apply_edits_to_str_is_independent_of_order_at_equal_starts() {
+        let insert_first = [Edit::insert(3, "X"), Edit::delete(3, 6)];
+        let delete_first = [Edit::delete(3, 6), Edit::insert(3, "X")];
+
+        assert_eq!(
+            apply_edits_to_str("abcdefgh", &insert_first),
+            Ok("abcXgh".to_string())
+        );
+        assert_eq!(
+            apply_edits_to_str("abcdefgh", &delete_first),
+            Ok("abcXgh".to_string())
+        );
+    }
+
+    #[test]
+    fn apply_edits_to_str_matches_buffer_apply() {
+        let input = "héllo wörld";
+        let edits = [
+            Edit::insert(0, "¡"),
+            Edit::replace(2, 4, "XY"),
+            Edit::delete(7, 9),
+            Edit::insert(11, "!"),
+        ];
+
+        let mut buffer = crate::buffer::Buffer::from_str(input);
+        buffer.apply(&edits).unwrap();
+
+        assert_eq!(
+            apply_edits_to_str(input, &edits),
+            Ok(buffer.content().to_string())
+        );
+        assert_eq!(
+            apply_edits_to_str(input, &edits),
+            Ok("¡héXYo wld!".to_string())
+        );
+    }
+
+    #[test]
+    fn apply_edits_to_str_applies_in_descending_order_regardless_of_input_order() {
+        let edits = [Edit::insert(1, "a"), Edit::insert(3, "b")];
+        let reversed = [Edit::insert(3, "b"), Edit::insert(1, "a")];
+
+        assert_eq!(apply_edits_to_str("xyz", &edits), Ok("xayzb".to_string()));
+        assert_eq!(
+            apply_edits_to_str("xyz", &reversed),
+            Ok("xayzb".to_string())
+        );
+    }
+}
@@ -1,13 +1,47 @@
-use crate::edit::Edit;
+use crate::edit::{apply_order, Edit, OverlappingEdits};
+
+#[cfg(feature = "persistent-undo")]
+use bincode::Options;
+#[cfg(feature = "persistent-undo")]
+use std::fs::{self, File};
+#[cfg(feature = "persistent-undo")]
+use std::io::{self, BufReader, BufWriter, Read, Write};
+#[cfg(feature = "persistent-undo")]
+use std::path::Path;
+
+#[cfg(feature = "persistent-undo")]
+const UNDO_FILE_MAGIC: &[u8; 6] = b"EWUNDO";
+#[cfg(feature = "persistent-undo")]
+const UNDO_FILE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "persistent-undo",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub enum EditKind {
+    UserEdit,
+    Format,
+    Paste,
+}
 
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "persistent-undo",
+    derive(serde::Serialize, serde::Deserialize)
+)]
 pub struct HistoryEntry {
     pub edits: Vec<Edit>,
     pub deleted_texts: Vec<String>,
     pub timestamp: std::time::SystemTime,
+    pub kind: Option<EditKind>,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "persistent-undo",
+    derive(serde::Serialize, serde::Deserialize)
+)]
 pub struct History {
     undo_stack: Vec<HistoryEntry>,
     redo_stack: Vec<HistoryEntry>,
@@ -22,7 +56,16 @@ impl History {
     }
 
     pub fn record(&mut self, edits: Vec<Edit>, deleted_texts: Vec<String>) {
-        if self.should_ignore_edits(&edits) {
+        self.record_with_kind(edits, deleted_texts, None);
+    }
+
+    pub fn record_with_kind(
+        &mut self,
+        edits: Vec<Edit>,
+        deleted_texts: Vec<String>,
+        kind: Option<EditKind>,
+    ) {
+        if self.should_ignore_edits(&edits, &deleted_texts) {
             return;
         }
 
@@ -30,6 +73,7 @@ impl History {
             edits,
             deleted_texts,
             timestamp: std::time::SystemTime::now(),
+            kind,
         };
 
         self.undo_stack.push(entry);
@@ -52,6 +96,29 @@ impl History {
         Some(edits_to_replay)
     }
 
+    // The entry the next undo would revert, e.g. to show "undo paste"
+    pub fn peek_undo(&self) -> Option<&HistoryEntry> {
+        self.undo_stack.last()
+    }
+
+    pub fn peek_redo(&self) -> Option<&HistoryEntry> {
+        self.redo_stack.last()
+    }
+
+    // The char range spanned by edits once applied, e.g. the result of undo()
+    // or redo(), so a UI can invalidate just that region. None for an empty
+    // or overlapping batch, which Buffer::apply leaves untouched
+    pub fn affected_range(edits: &[Edit]) -> Option<(usize, usize)> {
+        let shifted = Self::shift_into_result(edits).ok()?;
+        let start = shifted.first()?.1.start;
+        let end = shifted
+            .iter()
+            .map(|(_, edit)| edit.start + edit.text.chars().count())
+            .max()?;
+
+        Some((start, end))
+    }
+
     pub fn can_undo(&self) -> bool {
         !self.undo_stack.is_empty()
     }
@@ -60,27 +127,66 @@ impl History {
         !self.redo_stack.is_empty()
     }
 
+    pub fn undo_depth(&self) -> usize {
+        self.undo_stack.len()
+    }
+
+    pub fn redo_depth(&self) -> usize {
+        self.redo_stack.len()
+    }
+
     pub fn clear(&mut self) {
         self.undo_stack.clear();
         self.redo_stack.clear();
     }
 
-    fn should_ignore_edits(&self, edits: &[Edit]) -> bool {
-        edits.is_empty() || edits.iter().all(|edit| edit.is_noop())
+    fn should_ignore_edits(&self, edits: &[Edit], deleted_texts: &[String]) -> bool {
+        edits.is_empty()
+            || edits.iter().all(|edit| edit.is_noop())
+            || !Self::is_invertible(edits, deleted_texts)
     }
 
+    // Overlapping batches are never applied by Buffer::apply, so there is
+    // nothing to undo for them, and every edit needs the text it deleted
+    fn is_invertible(edits: &[Edit], deleted_texts: &[String]) -> bool {
+        edits.len() == deleted_texts.len() && apply_order(edits).is_ok()
+    }
+
+    // Each edit moved to where it sits in the edited text, i.e. shifted by
+    // the length changes of the edits before it, with its original index
+    fn shift_into_result(edits: &[Edit]) -> Result<Vec<(usize, Edit)>, OverlappingEdits> {
+        let mut offset: isize = 0;
+
+        let shifted = apply_order(edits)?
+            .into_iter()
+            .map(|index| {
+                let edit = &edits[index];
+                let start = edit.start.saturating_add_signed(offset);
+                let shifted_edit = Edit {
+                    start,
+                    end: start + (edit.end - edit.start),
+                    text: edit.text.clone(),
+                };
+
+                offset += edit.text.chars().count() as isize - (edit.end - edit.start) as isize;
+                (index, shifted_edit)
+            })
+            .collect();
+
+        Ok(shifted)
+    }
+
+    // Inverses are positioned in the edited text they will be applied to
     fn create_inverse_edits(&self, entry: &HistoryEntry) -> Vec<Edit> {
-        entry
-            .edits
-            .iter()
-            .zip(entry.deleted_texts.iter())
-            .rev() // Apply inverses in reverse order
-            .map(|(edit, deleted_text)| self.invert_edit(edit, deleted_text))
+        Self::shift_into_result(&entry.edits)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(index, edit)| self.invert_edit(&edit, &entry.deleted_texts[index]))
             .collect()
     }
 
     fn invert_insertion(&self, edit: &Edit) -> Edit {
-        Edit::delete(edit.start, edit.start + edit.text.len())
+        Edit::delete(edit.start, edit.start + edit.text.chars().count())
     }
 
     fn invert_deletion(&self, edit: &Edit, deleted_text: &str) -> Edit {
@@ -88,7 +194,11 @@ impl History {
     }
 
     fn invert_replacement(&self, edit: &Edit, deleted_text: &str) -> Edit {
-        Edit::replace(edit.start, edit.start + edit.text.len(), deleted_text)
+        Edit::replace(
+            edit.start,
+            edit.start + edit.text.chars().count(),
+            deleted_text,
+        )
     }
 
     fn invert_noop(&self, edit: &Edit) -> Edit {
@@ -106,8 +216,398 @@ impl History {
     }
 }
 
+#[cfg(feature = "persistent-undo")]
+impl History {
+    // The content hash is stored alongside the entries so a history saved
+    // for one version of a file is never replayed against another
+    pub fn save_to(&self, path: &Path, content_hash: u64) -> io::Result<()> {
+        let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(".tmp");
+        let temp_path = path.with_file_name(temp_name);
+
+        // Writing beside the target and renaming means a crash mid-save never
+        // leaves a truncated undo file behind
+        let result = self
+            .write_undo_file(&temp_path, content_hash)
+            .and_then(|()| fs::rename(&temp_path, path));
+
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+
+        result
+    }
+
+    // Returns None when the file changed since the history was saved, or the
+    // history was written in another format version
+    pub fn load_from(path: &Path, content_hash: u64) -> io::Result<Option<Self>> {
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+        let mut magic = [0; UNDO_FILE_MAGIC.len()];
+        let mut version = [0; 4];
+
+        reader.read_exact(&mut magic)?;
+
+        if &magic != UNDO_FILE_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not an undo file",
+            ));
+        }
+
+        reader.read_exact(&mut version)?;
+
+        if u32::from_le_bytes(version) != UNDO_FILE_VERSION {
+            return Ok(None);
+        }
+
+        // Nothing in the file can be longer than the file itself, so the limit
+        // turns a corrupt length prefix into an error instead of a huge allocation
+        let (saved_hash, history): (u64, Self) = Self::bincode_options()
+            .with_limit(file_len)
+            .deserialize_from(reader)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+
+        let mut entries = history.undo_stack.iter().chain(&history.redo_stack);
+
+        if !entries.all(|entry| Self::is_invertible(&entry.edits, &entry.deleted_texts)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "undo entry cannot be inverted",
+            ));
+        }
+
+        Ok((saved_hash == content_hash).then_some(history))
+    }
+
+    fn write_undo_file(&self, path: &Path, content_hash: u64) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        writer.write_all(UNDO_FILE_MAGIC)?;
+        writer.write_all(&UNDO_FILE_VERSION.to_le_bytes())?;
+        Self::bincode_options()
+            .serialize_into(&mut writer, &(content_hash, self))
+            .map_err(io::Error::other)?;
+
+        // Dropping a BufWriter silently ignores flush errors
+        writer
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?
+            .sync_all()
+    }
+
+    // Fixed-width integers match the layout of bincode's plain serialize
+    fn bincode_options() -> impl Options {
+        bincode::DefaultOptions::new().with_fixint_encoding()
+    }
+}
+
 impl Default for History {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::Buffer;
+
+    fn apply_and_record(buffer: &mut Buffer, history: &mut History, edits: Vec<Edit>) {
+        let deleted_texts = buffer.apply(&edits).unwrap();
+        history.record(edits, deleted_texts);
+    }
+
+    #[test]
+    fn undo_restores_batch_of_separate_edits() {
+        let mut buffer = Buffer::from_str("hello world");
+        let mut history = History::new();
+
+        let edits = vec![
+            Edit::delete(0, 2),
+            Edit::replace(6, 11, "there"),
+            Edit::insert(5, ","),
+        ];
+        apply_and_record(&mut buffer, &mut history, edits);
+        assert_eq!(buffer.content().to_string(), "llo, there");
+
+        buffer.apply(&history.undo().unwrap()).unwrap();
+        assert_eq!(buffer.content().to_string(), "hello world");
+
+        buffer.apply(&history.redo().unwrap()).unwrap();
+        assert_eq!(buffer.content().to_string(), "llo, there");
+    }
+
+    #[test]
+    fn undo_restores_insert_and_delete_sharing_a_start() {
+        let mut buffer = Buffer::from_str("abcdefgh");
+        let mut history = History::new();
+
+        apply_and_record(
+            &mut buffer,
+            &mut history,
+            vec![Edit::delete(3, 6), Edit::insert(3, "X")],
+        );
+        assert_eq!(buffer.content().to_string(), "abcXgh");
+
+        buffer.apply(&history.undo().unwrap()).unwrap();
+        assert_eq!(buffer.content().to_string(), "abcdefgh");
+    }
+
+    #[test]
+    fn overlapping_batch_is_not_recorded() {
+        let mut history = History::new();
+
+        history.record(
+            vec![Edit::replace(0, 5, "x"), Edit::replace(3, 8, "y")],
+            vec![String::new(), String::new()],
+        );
+
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn affected_range_matches_undone_edit() {
+        let mut buffer = Buffer::from_str("hello world");
+        let mut history = History::new();
+
+        apply_and_record(
+            &mut buffer,
+            &mut history,
+            vec![Edit::replace(6, 11, "there!")],
+        );
+
+        let inverse = history.undo().unwrap();
+        assert_eq!(History::affected_range(&inverse), Some((6, 11)));
+
+        buffer.apply(&inverse).unwrap();
+        assert_eq!(buffer.content().slice(6..11).to_string(), "world");
+    }
+
+    #[test]
+    fn affected_range_accounts_for_earlier_edits() {
+        let edits = [Edit::insert(8, "XYZ"), Edit::delete(0, 2)];
+
+        assert_eq!(History::affected_range(&edits), Some((0, 9)));
+    }
+
+    #[test]
+    fn affected_range_of_pure_deletion_is_empty_at_its_start() {
+        assert_eq!(History::affected_range(&[Edit::delete(3, 6)]), Some((3, 3)));
+    }
+
+    #[test]
+    fn affected_range_is_none_for_batches_apply_rejects() {
+        let overlapping = [Edit::replace(0, 5, "x"), Edit::replace(3, 8, "y")];
+
+        assert_eq!(History::affected_range(&[]), None);
+        assert_eq!(History::affected_range(&overlapping), None);
+    }
+
+    #[cfg(feature = "persistent-undo")]
+    fn undo_file(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("ew-{}-{name}.undo", std::process::id()))
+    }
+
+    #[cfg(feature = "persistent-undo")]
+    #[test]
+    fn saved_history_restores_undo_after_reload() {
+        let path = undo_file("round-trip");
+        let mut buffer = Buffer::from_str("hello world");
+        let mut history = History::new();
+
+        let edits = vec![Edit::insert(5, ","), Edit::replace(6, 11, "there")];
+        let deleted_texts = buffer.apply(&edits).unwrap();
+        history.record_with_kind(edits, deleted_texts, Some(EditKind::Paste));
+        history.save_to(&path, buffer.content_hash()).unwrap();
+
+        let mut restored = History::load_from(&path, buffer.content_hash())
+            .unwrap()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(restored.peek_undo().unwrap().kind, Some(EditKind::Paste));
+
+        buffer.apply(&restored.undo().unwrap()).unwrap();
+        assert_eq!(buffer.content().to_string(), "hello world");
+    }
+
+    #[cfg(feature = "persistent-undo")]
+    #[test]
+    fn saved_history_is_discarded_when_content_changed() {
+        let path = undo_file("stale");
+        let mut history = History::new();
+
+        history.record(vec![Edit::insert(0, "a")], vec![String::new()]);
+        history.save_to(&path, 1).unwrap();
+
+        let loaded = History::load_from(&path, 2).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(loaded.is_none());
+    }
+
+    #[cfg(feature = "persistent-undo")]
+    #[test]
+    fn load_rejects_files_without_header() {
+        let path = undo_file("garbage");
+
+        std::fs::write(&path, b"not an undo file").unwrap();
+        let error = History::load_from(&path, 0).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(feature = "persistent-undo")]
+    #[test]
+    fn load_ignores_other_format_versions() {
+        let path = undo_file("old-version");
+        let mut contents = UNDO_FILE_MAGIC.to_vec();
+        contents.extend_from_slice(&(UNDO_FILE_VERSION + 1).to_le_bytes());
+
+        std::fs::write(&path, contents).unwrap();
+        let loaded = History::load_from(&path, 0).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(loaded.is_none());
+    }
+
+    #[test]
+    fn can_redo_only_after_undo_until_next_edit() {
+        let mut buffer = Buffer::from_str("abc");
+        let mut history = History::new();
+
+        apply_and_record(&mut buffer, &mut history, vec![Edit::insert(3, "d")]);
+        assert!(history.can_undo());
+        assert!(!history.can_redo());
+
+        buffer.apply(&history.undo().unwrap()).unwrap();
+        assert!(history.can_redo());
+        assert_eq!((history.undo_depth(), history.redo_depth()), (0, 1));
+
+        apply_and_record(&mut buffer, &mut history, vec![Edit::insert(0, "z")]);
+        assert!(!history.can_redo());
+        assert_eq!((history.undo_depth(), history.redo_depth()), (1, 0));
+    }
+
+    #[test]
+    fn undo_of_multibyte_insert_removes_chars_not_bytes() {
+        let mut buffer = Buffer::from_str("x");
+        let mut history = History::new();
+
+        apply_and_record(&mut buffer, &mut history, vec![Edit::insert(0, "café")]);
+        assert_eq!(history.undo(), Some(vec![Edit::delete(0, 4)]));
+    }
+
+    #[test]
+    fn undo_of_multibyte_replacement_restores_text() {
+        let mut buffer = Buffer::from_str("café x");
+        let mut history = History::new();
+
+        apply_and_record(&mut buffer, &mut history, vec![Edit::replace(5, 6, "éé")]);
+        buffer.apply(&history.undo().unwrap()).unwrap();
+
+        assert_eq!(buffer.content().to_string(), "café x");
+    }
+
+    #[test]
+    fn edit_kind_is_visible_on_top_undo_entry() {
+        let mut history = History::new();
+
+        history.record(vec![Edit::insert(0, "a")], vec![String::new()]);
+        assert_eq!(history.peek_undo().unwrap().kind, None);
+
+        history.record_with_kind(
+            vec![Edit::insert(1, "pasted")],
+            vec![String::new()],
+            Some(EditKind::Paste),
+        );
+        assert_eq!(history.peek_undo().unwrap().kind, Some(EditKind::Paste));
+    }
+
+    #[test]
+    fn edit_kind_moves_to_redo_stack_on_undo() {
+        let mut history = History::new();
+
+        history.record_with_kind(
+            vec![Edit::replace(0, 3, "fmt")],
+            vec!["old".to_string()],
+            Some(EditKind::Format),
+        );
+        history.undo();
+
+        assert!(history.peek_undo().is_none());
+        assert_eq!(history.peek_redo().unwrap().kind, Some(EditKind::Format));
+    }
+    #[cfg(feature = "persistent-undo")]
+    #[test]
+    fn load_rejects_corrupt_length_prefix() {
+        let path = undo_file("corrupt-length");
+        let mut contents = UNDO_FILE_MAGIC.to_vec();
+
+        contents.extend_from_slice(&UNDO_FILE_VERSION.to_le_bytes());
+        // Content hash, then one undo entry holding one edit at 0..0
+        for value in [0u64, 1, 1, 0, 0] {
+            contents.extend_from_slice(&value.to_le_bytes());
+        }
+
+        // An edit text claiming far more bytes than the file could hold
+        contents.extend_from_slice(&(1u64 << 40).to_le_bytes());
+
+        std::fs::write(&path, &contents).unwrap();
+        let error = History::load_from(&path, 0).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(feature = "persistent-undo")]
+    #[test]
+    fn save_replaces_existing_file_without_leaving_temp_file() {
+        let path = undo_file("replace");
+        let mut history = History::new();
+
+        std::fs::write(&path, b"stale contents").unwrap();
+        history.record(vec![Edit::insert(0, "a")], vec![String::new()]);
+        history.save_to(&path, 1).unwrap();
+
+        let temp_exists = path.with_extension("undo.tmp").exists();
+        let loaded = History::load_from(&path, 1).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(!temp_exists);
+        assert_eq!(loaded.map(|history| history.undo_depth()), Some(1));
+    }
+    #[test]
+    fn record_ignores_edits_without_matching_deleted_texts() {
+        let mut history = History::new();
+
+        history.record(vec![Edit::insert(0, "a")], vec![]);
+        history.record(vec![Edit::delete(0, 1)], vec!["a".into(), "b".into()]);
+
+        assert!(!history.can_undo());
+        assert_eq!(history.undo(), None);
+    }
+
+    #[cfg(feature = "persistent-undo")]
+    #[test]
+    fn load_rejects_entries_without_matching_deleted_texts() {
+        let path = undo_file("mismatched-entry");
+        let mut history = History::new();
+
+        history.undo_stack.push(HistoryEntry {
+            edits: vec![Edit::insert(0, "a")],
+            deleted_texts: vec![],
+            timestamp: std::time::SystemTime::now(),
+            kind: None,
+        });
+        history.save_to(&path, 0).unwrap();
+
+        let error = History::load_from(&path, 0).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1,3 +1,6 @@
+// The anchor stays put while the head is the moving end, so extending a
+// selection always moves the head and may leave it before the anchor. Both
+// are char indices, like Edit positions
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct Selection {
     pub anchor: usize,
@@ -13,6 +16,10 @@ impl Selection {
         self.anchor == self.head
     }
 
+    pub fn is_forward(&self) -> bool {
+        self.anchor <= self.head
+    }
+
     pub fn cursor_to(&mut self, position: usize) {
         self.anchor = position;
         self.head = position;
@@ -23,6 +30,12 @@ impl Selection {
         self.head = head;
     }
 
+    // Keeps both ends within 0..=max, e.g. after the buffer shrank
+    pub fn clamp(&mut self, max: usize) {
+        self.anchor = self.anchor.min(max);
+        self.head = self.head.min(max);
+    }
+
     pub fn range(&self) -> (usize, usize) {
         if self.anchor <= self.head {
             (self.anchor, self.head)
@@ -30,4 +43,174 @@ impl Selection {
             (self.head, self.anchor)
         }
     }
+
+    // Ranges are half-open, but a cursor still contains its own position
+    pub fn contains(&self, pos: usize) -> bool {
+        let (start, end) = self.range();
+
+        (start..end).contains(&pos) || (start == end && pos == start)
+    }
+
+    pub fn overlaps(&self, other: &Selection) -> bool {
+        let (start, end) = self.range();
+        let (other_start, other_end) = other.range();
+
+        match (self.is_cursor(), other.is_cursor()) {
+            (true, _) => other.contains(start),
+            (_, true) => self.contains(other_start),
+            _ => start < other_end && other_start < end,
+        }
+    }
+
+    // Spans both selections, keeping the direction of self
+    pub fn merge(&self, other: &Selection) -> Selection {
+        let (start, end) = self.range();
+        let (other_start, other_end) = other.range();
+
+        self.with_direction(start.min(other_start), end.max(other_end))
+    }
+
+    pub fn intersect(&self, other: &Selection) -> Option<Selection> {
+        if !self.overlaps(other) {
+            return None;
+        }
+
+        let (start, end) = self.range();
+        let (other_start, other_end) = other.range();
+
+        Some(self.with_direction(start.max(other_start), end.min(other_end)))
+    }
+
+    fn with_direction(&self, start: usize, end: usize) -> Selection {
+        if self.is_forward() {
+            Selection::new(start, end)
+        } else {
+            Selection::new(end, start)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contains_is_half_open() {
+        let selection = Selection::new(2, 5);
+
+        assert!(!selection.contains(1));
+        assert!(selection.contains(2));
+        assert!(selection.contains(4));
+        assert!(!selection.contains(5));
+    }
+
+    #[test]
+    fn cursor_contains_only_its_position() {
+        let cursor = Selection::new(3, 3);
+
+        assert!(cursor.contains(3));
+        assert!(!cursor.contains(2));
+        assert!(!cursor.contains(4));
+    }
+
+    #[test]
+    fn adjacent_selections_do_not_overlap() {
+        let left = Selection::new(0, 3);
+        let right = Selection::new(3, 5);
+
+        assert!(!left.overlaps(&right));
+        assert_eq!(left.intersect(&right), None);
+        assert_eq!(left.merge(&right), Selection::new(0, 5));
+    }
+
+    #[test]
+    fn nested_selection_intersects_to_inner() {
+        let outer = Selection::new(0, 10);
+        let inner = Selection::new(3, 6);
+
+        assert!(outer.overlaps(&inner));
+        assert!(inner.overlaps(&outer));
+        assert_eq!(outer.intersect(&inner), Some(Selection::new(3, 6)));
+        assert_eq!(inner.merge(&outer), Selection::new(0, 10));
+    }
+
+    #[test]
+    fn disjoint_selections_merge_across_gap() {
+        let left = Selection::new(0, 2);
+        let right = Selection::new(5, 7);
+
+        assert!(!left.overlaps(&right));
+        assert_eq!(left.intersect(&right), None);
+        assert_eq!(left.merge(&right), Selection::new(0, 7));
+    }
+
+    #[test]
+    fn zero_length_selections_overlap_what_contains_them() {
+        let range = Selection::new(2, 5);
+
+        assert!(Selection::new(2, 2).overlaps(&range));
+        assert!(range.overlaps(&Selection::new(4, 4)));
+        assert!(!Selection::new(5, 5).overlaps(&range));
+        assert!(Selection::new(3, 3).overlaps(&Selection::new(3, 3)));
+        assert!(!Selection::new(3, 3).overlaps(&Selection::new(4, 4)));
+        assert!(!Selection::new(4, 4).overlaps(&Selection::new(3, 3)));
+        assert_eq!(
+            range.intersect(&Selection::new(4, 4)),
+            Some(Selection::new(4, 4))
+        );
+    }
+
+    #[test]
+    fn merge_and_intersect_keep_direction_of_self() {
+        let backward = Selection::new(6, 2);
+
+        assert_eq!(backward.merge(&Selection::new(5, 9)), Selection::new(9, 2));
+        assert_eq!(
+            backward.intersect(&Selection::new(5, 9)),
+            Some(Selection::new(6, 5))
+        );
+    }
+
+    #[test]
+    fn is_forward_follows_head_relative_to_anchor() {
+        assert!(Selection::new(2, 5).is_forward());
+        assert!(!Selection::new(5, 2).is_forward());
+        assert!(Selection::new(3, 3).is_forward());
+    }
+
+    #[test]
+    fn range_normalizes_backward_selection() {
+        let backward = Selection::new(5, 2);
+
+        assert_eq!(backward.range(), (2, 5));
+        assert_eq!(backward.head, 2);
+    }
+
+    #[test]
+    fn clamp_pulls_both_ends_within_bounds() {
+        let mut selection = Selection::new(12, 20);
+        selection.clamp(10);
+
+        assert_eq!(selection, Selection::new(10, 10));
+    }
+
+    #[test]
+    fn clamp_leaves_valid_selection_untouched() {
+        let mut selection = Selection::new(8, 2);
+        selection.clamp(10);
+
+        assert_eq!(selection, Selection::new(8, 2));
+    }
+
+    #[test]
+    fn clamp_after_buffer_shrinks() {
+        let mut buffer = crate::buffer::Buffer::from_str("hello world");
+        let mut selection = Selection::new(6, 11);
+
+        buffer.apply(&[crate::edit::Edit::delete(3, 11)]).unwrap();
+        selection.clamp(buffer.len_chars());
+
+        assert_eq!(selection, Selection::new(3, 3));
+        assert!(buffer.try_slice(selection.anchor, selection.head).is_some());
+    }
 }